@@ -1,125 +1,604 @@
-use std::cmp::Ordering;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::fs::OpenOptions;
 use std::io;
 use std::io::Write;
+use std::str::FromStr;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+/// Number of decimal digits kept for both prices and quantities.
+const DECIMALS: u32 = 4;
+/// 10^DECIMALS: the factor user-facing decimal input/output is scaled by.
+const SCALE: i64 = 10_000;
+
+/// Parses a decimal string like `"10.5"` into its fixed-point integer
+/// representation (`10.5` -> `105000` at `DECIMALS = 4`). Rejects input with
+/// more fractional digits than `DECIMALS` rather than silently truncating.
+fn parse_scaled(s: &str) -> Result<i64, ()> {
+    let s = s.trim();
+    let (sign, s) = match s.strip_prefix('-') {
+        Some(rest) => (-1i64, rest),
+        None => (1i64, s),
+    };
+
+    let mut split = s.splitn(2, '.');
+    let int_part = split.next().unwrap_or("");
+    let frac_part = split.next().unwrap_or("");
+
+    if int_part.is_empty() && frac_part.is_empty() {
+        return Err(());
+    }
+    if frac_part.len() > DECIMALS as usize {
+        return Err(());
+    }
+
+    let int_val: i64 = if int_part.is_empty() {
+        0
+    } else {
+        int_part.parse().map_err(|_| ())?
+    };
+
+    let mut frac_str = frac_part.to_string();
+    while frac_str.len() < DECIMALS as usize {
+        frac_str.push('0');
+    }
+    let frac_val: i64 = if frac_str.is_empty() {
+        0
+    } else {
+        frac_str.parse().map_err(|_| ())?
+    };
+
+    Ok(sign * (int_val * SCALE + frac_val))
+}
+
+fn format_scaled(v: i64) -> String {
+    let sign = if v < 0 { "-" } else { "" };
+    let v_abs = v.unsigned_abs();
+    format!(
+        "{}{}.{:0width$}",
+        sign,
+        v_abs / SCALE as u64,
+        v_abs % SCALE as u64,
+        width = DECIMALS as usize
+    )
+}
+
+/// A price, scaled by `SCALE` and stored as an exact integer so comparisons
+/// are total (no NaN) and arithmetic never accumulates rounding error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+struct Price(i64);
+
+impl FromStr for Price {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_scaled(s).map(Price)
+    }
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format_scaled(self.0))
+    }
+}
+
+/// A quantity, scaled the same way as `Price`. Exhaustion checks against it
+/// (`quantity == Qty::ZERO`) are exact integer comparisons.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+struct Qty(i64);
+
+impl Qty {
+    const ZERO: Qty = Qty(0);
+}
+
+impl FromStr for Qty {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_scaled(s).map(Qty)
+    }
+}
+
+impl fmt::Display for Qty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format_scaled(self.0))
+    }
+}
+
+impl std::ops::Add for Qty {
+    type Output = Qty;
+    fn add(self, rhs: Self) -> Qty {
+        Qty(self.0 + rhs.0)
+    }
+}
+
+impl std::ops::Sub for Qty {
+    type Output = Qty;
+    fn sub(self, rhs: Self) -> Qty {
+        Qty(self.0 - rhs.0)
+    }
+}
+
+impl std::ops::SubAssign for Qty {
+    fn sub_assign(&mut self, rhs: Self) {
+        self.0 -= rhs.0;
+    }
+}
+
+impl std::iter::Sum for Qty {
+    fn sum<I: Iterator<Item = Qty>>(iter: I) -> Qty {
+        iter.fold(Qty::ZERO, |a, b| a + b)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 enum OrderType {
     Buy,
     Sell,
 }
 
-#[derive(Debug, Clone)]
+/// How long an order is allowed to live before any unfilled remainder is
+/// discarded instead of resting in the book.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum TimeInForce {
+    /// Rests in the book until matched or explicitly cancelled.
+    GoodTilCancel,
+    /// Matches what it can immediately; any remainder is discarded, never rests.
+    ImmediateOrCancel,
+    /// Only executes if the entire quantity can be filled immediately;
+    /// otherwise the order is rejected and produces no trades at all.
+    FillOrKill,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct Order {
     id: u64,
     order_type: OrderType,
-    price: f64,
-    quantity: f64,
+    price: Price,
+    /// Quantity still resting in the book (i.e. not yet traded).
+    quantity: Qty,
+    /// Quantity the order was originally submitted for. This never changes
+    /// after creation, so `original_quantity - quantity` always equals the
+    /// amount already traded away and cannot be cancelled back.
+    original_quantity: Qty,
+    /// Monotonically increasing insertion order, used to break ties between
+    /// orders resting at the same price (price-time priority).
+    seq: u64,
+    /// Market orders match the best available opposite price regardless of
+    /// `price` and never rest in the book; `price` holds a sentinel
+    /// (`Price(i64::MIN)`/`Price(i64::MAX)`) so they still sort to the front of their
+    /// side.
+    is_market: bool,
+    time_in_force: TimeInForce,
 }
 
-#[derive(Debug, Clone)]
+impl Order {
+    fn new_limit(
+        id: u64,
+        order_type: OrderType,
+        price: Price,
+        quantity: Qty,
+        time_in_force: TimeInForce,
+    ) -> Self {
+        Self {
+            id,
+            order_type,
+            price,
+            quantity,
+            original_quantity: quantity,
+            seq: 0,
+            is_market: false,
+            time_in_force,
+        }
+    }
+
+    fn new_market(id: u64, order_type: OrderType, quantity: Qty) -> Self {
+        let price = match order_type {
+            OrderType::Buy => Price(i64::MAX),
+            OrderType::Sell => Price(i64::MIN),
+        };
+
+        Self {
+            id,
+            order_type,
+            price,
+            quantity,
+            original_quantity: quantity,
+            seq: 0,
+            is_market: true,
+            time_in_force: TimeInForce::ImmediateOrCancel,
+        }
+    }
+
+    fn filled_quantity(&self) -> Qty {
+        self.original_quantity - self.quantity
+    }
+}
+
+/// What happened to an order after `OrderBook::add_order` processed it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OrderOutcome {
+    /// Fully matched immediately.
+    Filled,
+    /// Unmatched (or partially matched) quantity now rests in the book.
+    Resting,
+    /// IOC or market order: `filled` matched immediately, `cancelled` was
+    /// discarded rather than left resting.
+    PartiallyFilledThenCancelled { filled: Qty, cancelled: Qty },
+    /// FOK order: could not be fully filled immediately, so it was rejected
+    /// before touching the book and produced no trades.
+    RejectedFillOrKill,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct Trade {
     buy_id: u64,
     sell_id: u64,
-    price: f64,
-    quantity: f64,
+    price: Price,
+    quantity: Qty,
 }
 
-#[derive(Debug)]
-struct OrderBook {
+/// On-disk form of an `OrderBook`, used by the `save`/`load` commands.
+/// Resting orders are flattened out of the price-level `BTreeMap`s into
+/// plain `Vec`s; `restore` re-threads them back into the map structure.
+#[derive(Debug, Serialize, Deserialize)]
+struct BookSnapshot {
     buy_orders: Vec<Order>,
     sell_orders: Vec<Order>,
     trades: Vec<Trade>,
+    next_seq: u64,
+    next_id: u64,
+}
+
+/// Best price and aggregate resting quantity at that price, for one side of
+/// the book.
+type PriceLevel = (Price, Qty);
+
+/// Buy orders are keyed by `Reverse<Price>` so that a plain ascending
+/// `BTreeMap` iteration (`.keys().next()`) yields the *highest* price first;
+/// sell orders are keyed directly by `Price` so iteration yields the
+/// *lowest* price first. Within a price level, orders sit in a `VecDeque`
+/// in arrival order, so the front of the deque is always the oldest order
+/// at that price (time priority).
+#[derive(Debug)]
+struct OrderBook {
+    buy_orders: BTreeMap<Reverse<Price>, VecDeque<Order>>,
+    sell_orders: BTreeMap<Price, VecDeque<Order>>,
+    trades: Vec<Trade>,
+    next_seq: u64,
+    /// Each market owns its own order-id space, so ids from one market never
+    /// collide with another's.
+    next_id: u64,
+    /// When set (via the `log` command), every executed `Trade` is appended
+    /// to this file as one JSON object per line, as it happens.
+    event_log: Option<std::fs::File>,
 }
 
 impl OrderBook {
     fn new() -> Self {
         Self {
-            buy_orders: Vec::new(),
-            sell_orders: Vec::new(),
+            buy_orders: BTreeMap::new(),
+            sell_orders: BTreeMap::new(),
             trades: Vec::new(),
+            next_seq: 0,
+            next_id: 1,
+            event_log: None,
         }
     }
 
-    fn add_order(&mut self, order: Order) {
-        match order.order_type {
-            OrderType::Buy => self.buy_orders.push(order),
-            OrderType::Sell => self.sell_orders.push(order),
+    fn next_order_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    /// Opens (creating if needed) `path` and appends every trade executed
+    /// from now on to it, one JSON object per line.
+    fn enable_event_log(&mut self, path: &str) -> io::Result<()> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        self.event_log = Some(file);
+        Ok(())
+    }
+
+    /// Snapshots resting orders, trade history, and the `next_id`/`next_seq`
+    /// counters so the book can be restored later with `restore`. The event
+    /// log (a live file handle, not book state) is not part of the snapshot.
+    fn snapshot(&self) -> BookSnapshot {
+        BookSnapshot {
+            buy_orders: self
+                .buy_orders
+                .values()
+                .flat_map(|q| q.iter().cloned())
+                .collect(),
+            sell_orders: self
+                .sell_orders
+                .values()
+                .flat_map(|q| q.iter().cloned())
+                .collect(),
+            trades: self.trades.clone(),
+            next_seq: self.next_seq,
+            next_id: self.next_id,
         }
-        self.sort_books();
-        self.match_orders();
     }
 
-    fn sort_books(&mut self) {
-        self.buy_orders
-            .sort_by(|a, b| b.price.partial_cmp(&a.price).unwrap_or(Ordering::Equal));
-        self.sell_orders
-            .sort_by(|a, b| a.price.partial_cmp(&b.price).unwrap_or(Ordering::Equal));
+    fn restore(snapshot: BookSnapshot) -> Self {
+        let mut book = OrderBook::new();
+        book.next_seq = snapshot.next_seq;
+        book.next_id = snapshot.next_id;
+        book.trades = snapshot.trades;
+
+        for order in snapshot.buy_orders {
+            book.buy_orders
+                .entry(Reverse(order.price))
+                .or_default()
+                .push_back(order);
+        }
+        for order in snapshot.sell_orders {
+            book.sell_orders
+                .entry(order.price)
+                .or_default()
+                .push_back(order);
+        }
+
+        book
+    }
+
+    fn save_to_file(&self, path: &str) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(&self.snapshot()).map_err(io::Error::other)?;
+        std::fs::write(path, json)
     }
 
-    fn match_orders(&mut self) {
-        let mut trades = Vec::new();
-        let mut i = 0;
-
-        while i < self.buy_orders.len() {
-            let mut j = 0;
-            while j < self.sell_orders.len() {
-                let buy = &mut self.buy_orders[i];
-                let sell = &mut self.sell_orders[j];
-
-                if buy.price >= sell.price {
-                    let qty = buy.quantity.min(sell.quantity);
-                    let trade_price = sell.price;
-
-                    trades.push(Trade {
-                        buy_id: buy.id,
-                        sell_id: sell.id,
-                        price: trade_price,
-                        quantity: qty,
-                    });
-
-                    buy.quantity -= qty;
-                    sell.quantity -= qty;
-
-                    if sell.quantity == 0.0 {
-                        self.sell_orders.remove(j);
-                    } else {
-                        j += 1;
-                    }
+    fn load_from_file(path: &str) -> io::Result<Self> {
+        let data = std::fs::read_to_string(path)?;
+        let snapshot: BookSnapshot = serde_json::from_str(&data).map_err(io::Error::other)?;
+        Ok(Self::restore(snapshot))
+    }
+
+    fn add_order(&mut self, mut order: Order) -> OrderOutcome {
+        order.seq = self.next_seq;
+        self.next_seq += 1;
+
+        if order.time_in_force == TimeInForce::FillOrKill
+            && self.available_to_match(&order) < order.quantity
+        {
+            return OrderOutcome::RejectedFillOrKill;
+        }
+
+        let id = order.id;
+        let order_type = order.order_type;
+        let original_quantity = order.original_quantity;
 
-                    if buy.quantity == 0.0 {
-                        break;
+        match order_type {
+            OrderType::Buy => self
+                .buy_orders
+                .entry(Reverse(order.price))
+                .or_default()
+                .push_back(order),
+            OrderType::Sell => self
+                .sell_orders
+                .entry(order.price)
+                .or_default()
+                .push_back(order),
+        }
+
+        self.match_orders();
+
+        match self.remaining_quantity(id, order_type) {
+            None => OrderOutcome::Filled,
+            Some(remaining) => {
+                let is_market_or_ioc = self
+                    .remaining_order(id, order_type)
+                    .map(|o| o.is_market || o.time_in_force == TimeInForce::ImmediateOrCancel)
+                    .unwrap_or(false);
+
+                if is_market_or_ioc {
+                    self.cancel_order(id);
+                    OrderOutcome::PartiallyFilledThenCancelled {
+                        filled: original_quantity - remaining,
+                        cancelled: remaining,
                     }
                 } else {
-                    j += 1;
+                    OrderOutcome::Resting
                 }
             }
+        }
+    }
+
+    /// Sums the opposite side's resting quantity that `order` could trade
+    /// against: for a market order, all of it; for a limit order, only the
+    /// price levels that cross its limit price. Used to decide up front
+    /// whether a fill-or-kill order can be satisfied atomically.
+    fn available_to_match(&self, order: &Order) -> Qty {
+        match order.order_type {
+            OrderType::Buy => self
+                .sell_orders
+                .iter()
+                .take_while(|(&key, _)| order.is_market || key <= order.price)
+                .flat_map(|(_, queue)| queue.iter())
+                .map(|o| o.quantity)
+                .sum(),
+            OrderType::Sell => self
+                .buy_orders
+                .iter()
+                .take_while(|(key, _)| order.is_market || key.0 >= order.price)
+                .flat_map(|(_, queue)| queue.iter())
+                .map(|o| o.quantity)
+                .sum(),
+        }
+    }
+
+    fn remaining_order(&self, id: u64, order_type: OrderType) -> Option<&Order> {
+        match order_type {
+            OrderType::Buy => self
+                .buy_orders
+                .values()
+                .flat_map(|q| q.iter())
+                .find(|o| o.id == id),
+            OrderType::Sell => self
+                .sell_orders
+                .values()
+                .flat_map(|q| q.iter())
+                .find(|o| o.id == id),
+        }
+    }
+
+    fn remaining_quantity(&self, id: u64, order_type: OrderType) -> Option<Qty> {
+        self.remaining_order(id, order_type).map(|o| o.quantity)
+    }
+
+    /// Removes the order with `id` from whichever side it rests on.
+    ///
+    /// Only the still-resting `quantity` is ever cancelled: any portion
+    /// already consumed by a `Trade` has reduced `quantity` in place, so it
+    /// is simply not part of the order being removed here. Returns `true`
+    /// if an order with that id was found and removed.
+    fn cancel_order(&mut self, id: u64) -> bool {
+        if let Some((&key, queue)) = self
+            .buy_orders
+            .iter_mut()
+            .find(|(_, q)| q.iter().any(|o| o.id == id))
+        {
+            let order = queue.iter().find(|o| o.id == id).unwrap();
+            debug_assert_eq!(order.filled_quantity() + order.quantity, order.original_quantity);
 
-            if self.buy_orders[i].quantity == 0.0 {
-                self.buy_orders.remove(i);
-            } else {
-                i += 1;
+            queue.retain(|o| o.id != id);
+            if queue.is_empty() {
+                self.buy_orders.remove(&key);
             }
+            return true;
         }
 
-        self.trades.extend(trades);
+        if let Some((&key, queue)) = self
+            .sell_orders
+            .iter_mut()
+            .find(|(_, q)| q.iter().any(|o| o.id == id))
+        {
+            let order = queue.iter().find(|o| o.id == id).unwrap();
+            debug_assert_eq!(order.filled_quantity() + order.quantity, order.original_quantity);
+
+            queue.retain(|o| o.id != id);
+            if queue.is_empty() {
+                self.sell_orders.remove(&key);
+            }
+            return true;
+        }
+
+        false
+    }
+
+    /// Repeatedly crosses the best bid against the best ask, consuming
+    /// orders from the front of each price level's queue (oldest first)
+    /// until the two sides no longer cross or one side runs dry.
+    fn match_orders(&mut self) {
+        while let (Some(buy_key), Some(sell_key)) = (
+            self.buy_orders.keys().next().copied(),
+            self.sell_orders.keys().next().copied(),
+        ) {
+            if buy_key.0 < sell_key {
+                break;
+            }
+
+            let buy_queue = self.buy_orders.get_mut(&buy_key).unwrap();
+            let sell_queue = self.sell_orders.get_mut(&sell_key).unwrap();
+
+            let (trade, buy_done, sell_done) = {
+                let buy = buy_queue.front_mut().unwrap();
+                let sell = sell_queue.front_mut().unwrap();
+
+                let qty = buy.quantity.min(sell.quantity);
+                // A market order carries a sentinel price (Price(i64::MIN/MAX)),
+                // not a real one, so it can never be used as the trade price:
+                // the execution price is always the limit side's price. When
+                // neither side is a market order this keeps the existing
+                // convention of executing at the sell side's price.
+                let price = if sell.is_market { buy.price } else { sell.price };
+                let trade = Trade {
+                    buy_id: buy.id,
+                    sell_id: sell.id,
+                    price,
+                    quantity: qty,
+                };
+
+                buy.quantity -= qty;
+                sell.quantity -= qty;
+
+                (trade, buy.quantity == Qty::ZERO, sell.quantity == Qty::ZERO)
+            };
+
+            if let Some(file) = self.event_log.as_mut() {
+                if let Ok(line) = serde_json::to_string(&trade) {
+                    let _ = writeln!(file, "{}", line);
+                }
+            }
+            self.trades.push(trade);
+
+            if buy_done {
+                buy_queue.pop_front();
+            }
+            if sell_done {
+                sell_queue.pop_front();
+            }
+
+            if buy_queue.is_empty() {
+                self.buy_orders.remove(&buy_key);
+            }
+            if sell_queue.is_empty() {
+                self.sell_orders.remove(&sell_key);
+            }
+        }
+    }
+
+    /// Returns the best bid and best ask as `(price, aggregate qty at that
+    /// price)`, or `None` for a side with nothing resting.
+    fn best_quote(&self) -> (Option<PriceLevel>, Option<PriceLevel>) {
+        let best_bid = self.buy_orders.iter().next().map(|(key, queue)| {
+            let qty: Qty = queue.iter().map(|o| o.quantity).sum();
+            (key.0, qty)
+        });
+        let best_ask = self.sell_orders.iter().next().map(|(key, queue)| {
+            let qty: Qty = queue.iter().map(|o| o.quantity).sum();
+            (*key, qty)
+        });
+        (best_bid, best_ask)
+    }
+
+    /// Prints the inside spread as `QUOTE <bidqty> <bidprice> - <askqty>
+    /// <askprice>`, using `-` sentinels when a side has nothing resting.
+    fn show_quote(&self) {
+        let (bid, ask) = self.best_quote();
+
+        match bid {
+            Some((price, qty)) => print!("QUOTE {} {}", qty, price),
+            None => print!("QUOTE - -"),
+        }
+
+        match ask {
+            Some((price, qty)) => println!(" - {} {}", qty, price),
+            None => println!(" - - -"),
+        }
     }
 
     fn show_book(&self) {
         println!("\n===== order-book =====");
         println!("--- buy orders ---");
-        for o in &self.buy_orders {
-            println!(
-                "Buy #{:<3} | Price: {:<6} | Qty: {:<5}",
-                o.id, o.price, o.quantity
-            );
+        for queue in self.buy_orders.values() {
+            for o in queue {
+                println!(
+                    "Buy #{:<3} | Price: {:<6} | Qty: {:<5}",
+                    o.id, o.price, o.quantity
+                );
+            }
         }
 
         println!("--- sell orders ---");
-        for o in &self.sell_orders {
-            println!(
-                "Sell #{:<3} | Price: {:<6} | Qty: {:<5}",
-                o.id, o.price, o.quantity
-            );
+        for queue in self.sell_orders.values() {
+            for o in queue {
+                println!(
+                    "Sell #{:<3} | Price: {:<6} | Qty: {:<5}",
+                    o.id, o.price, o.quantity
+                );
+            }
         }
     }
 
@@ -134,20 +613,88 @@ impl OrderBook {
     }
 }
 
+/// Names a tradeable pair, e.g. `BTC/USD`: `base` is bought/sold in units of
+/// `quote`. Any two assets can define a market this way.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Symbol {
+    base: String,
+    quote: String,
+}
+
+impl Symbol {
+    fn new(base: &str, quote: &str) -> Self {
+        Self {
+            base: base.to_uppercase(),
+            quote: quote.to_uppercase(),
+        }
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.base, self.quote)
+    }
+}
+
+/// Hosts several independent `OrderBook`s, one per `Symbol`, each with its
+/// own resting orders, trade history, and order-id space.
+#[derive(Debug)]
+struct Exchange {
+    markets: HashMap<Symbol, OrderBook>,
+}
+
+impl Exchange {
+    fn new() -> Self {
+        Self {
+            markets: HashMap::new(),
+        }
+    }
+
+    /// Creates the market if it doesn't exist yet. Returns `true` if a new
+    /// (empty) market was created, `false` if it already existed.
+    fn create_market(&mut self, symbol: Symbol) -> bool {
+        match self.markets.entry(symbol) {
+            std::collections::hash_map::Entry::Occupied(_) => false,
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                entry.insert(OrderBook::new());
+                true
+            }
+        }
+    }
+
+    fn market_mut(&mut self, symbol: &Symbol) -> Option<&mut OrderBook> {
+        self.markets.get_mut(symbol)
+    }
+
+    fn market(&self, symbol: &Symbol) -> Option<&OrderBook> {
+        self.markets.get(symbol)
+    }
+}
+
 fn main() {
-    let mut book = OrderBook::new();
-    let mut next_id = 1;
+    let mut exchange = Exchange::new();
+    let mut current_market: Option<Symbol> = None;
 
     println!("📘 simple orderbook cli");
     println!("commands:");
-    println!("  add buy <price> <qty>");
-    println!("  add sell <price> <qty>");
+    println!("  market <base> <quote> - create/select a market, e.g. market BTC USD");
+    println!("  add buy <price> <qty> [ioc|fok]");
+    println!("  add sell <price> <qty> [ioc|fok]");
+    println!("  add buy market <qty>");
+    println!("  add sell market <qty>");
+    println!("  cancel <id>   - cancel a resting order");
     println!("  book          - show current order book");
     println!("  trades        - show trade history");
+    println!("  save <file>   - snapshot the current market to a JSON file");
+    println!("  load <file>   - restore the current market from a JSON file");
+    println!("  log <file>    - append every trade to <file> as JSON, one per line");
     println!("  exit          - quit the program");
 
     loop {
-        print!("\n> ");
+        match &current_market {
+            Some(symbol) => print!("\n[{}]> ", symbol),
+            None => print!("\n(no market)> "),
+        }
         io::stdout().flush().unwrap();
 
         let mut input = String::new();
@@ -165,9 +712,30 @@ fn main() {
         }
 
         match parts[0] {
+            "market" => {
+                if parts.len() != 3 {
+                    println!("Usage: market <base> <quote>");
+                    continue;
+                }
+
+                let symbol = Symbol::new(parts[1], parts[2]);
+                if exchange.create_market(symbol.clone()) {
+                    println!("market {} created.", symbol);
+                } else {
+                    println!("market {} already exists.", symbol);
+                }
+                current_market = Some(symbol);
+            }
+
             "add" => {
-                if parts.len() != 4 {
-                    println!("Usage: add <buy/sell> <price> <quantity>");
+                let Some(symbol) = current_market.clone() else {
+                    println!("no market selected. run: market <base> <quote>");
+                    continue;
+                };
+                let book = exchange.market_mut(&symbol).unwrap();
+
+                if parts.len() < 4 || parts.len() > 5 {
+                    println!("Usage: add <buy/sell> <price|market> <quantity> [ioc|fok]");
                     continue;
                 }
 
@@ -180,15 +748,27 @@ fn main() {
                     }
                 };
 
-                let price: f64 = match parts[2].parse() {
-                    Ok(p) => p,
-                    Err(_) => {
-                        println!("invalid price");
-                        continue;
+                let is_market = parts[2].eq_ignore_ascii_case("market");
+
+                if is_market && parts.len() != 4 {
+                    println!("market orders don't take a time-in-force suffix");
+                    continue;
+                }
+
+                let time_in_force = if parts.len() == 5 {
+                    match parts[4].to_lowercase().as_str() {
+                        "ioc" => TimeInForce::ImmediateOrCancel,
+                        "fok" => TimeInForce::FillOrKill,
+                        other => {
+                            println!("invalid time-in-force: {}", other);
+                            continue;
+                        }
                     }
+                } else {
+                    TimeInForce::GoodTilCancel
                 };
 
-                let qty: f64 = match parts[3].parse() {
+                let qty: Qty = match parts[3].parse() {
                     Ok(q) => q,
                     Err(_) => {
                         println!("invalid quantity");
@@ -196,28 +776,313 @@ fn main() {
                     }
                 };
 
-                book.add_order(Order {
-                    id: next_id,
-                    order_type,
-                    price,
-                    quantity: qty,
-                });
-                next_id += 1;
+                let order = if is_market {
+                    let id = book.next_order_id();
+                    Order::new_market(id, order_type, qty)
+                } else {
+                    let price: Price = match parts[2].parse() {
+                        Ok(p) => p,
+                        Err(_) => {
+                            println!("invalid price");
+                            continue;
+                        }
+                    };
+                    let id = book.next_order_id();
+                    Order::new_limit(id, order_type, price, qty, time_in_force)
+                };
+                let id = order.id;
+
+                match book.add_order(order) {
+                    OrderOutcome::Filled => println!("order #{} fully filled.", id),
+                    OrderOutcome::Resting => println!("order #{} added.", id),
+                    OrderOutcome::PartiallyFilledThenCancelled { filled, cancelled } => {
+                        println!(
+                            "order #{} filled {}, remainder {} cancelled.",
+                            id, filled, cancelled
+                        );
+                    }
+                    OrderOutcome::RejectedFillOrKill => {
+                        println!(
+                            "order #{} rejected: insufficient liquidity for fill-or-kill.",
+                            id
+                        );
+                    }
+                }
+                book.show_quote();
+            }
+
+            "cancel" => {
+                let Some(symbol) = current_market.clone() else {
+                    println!("no market selected. run: market <base> <quote>");
+                    continue;
+                };
+                let book = exchange.market_mut(&symbol).unwrap();
+
+                if parts.len() != 2 {
+                    println!("Usage: cancel <id>");
+                    continue;
+                }
+
+                let id: u64 = match parts[1].parse() {
+                    Ok(id) => id,
+                    Err(_) => {
+                        println!("invalid id");
+                        continue;
+                    }
+                };
 
-                println!("order added.");
+                if book.cancel_order(id) {
+                    println!("order #{} cancelled.", id);
+                } else {
+                    println!("no resting order #{} (already filled, already cancelled, or never existed).", id);
+                }
+                book.show_quote();
             }
 
             "book" => {
-                book.show_book();
+                let Some(symbol) = current_market.clone() else {
+                    println!("no market selected. run: market <base> <quote>");
+                    continue;
+                };
+                exchange.market(&symbol).unwrap().show_book();
             }
 
             "trades" => {
-                book.show_trades();
+                let Some(symbol) = current_market.clone() else {
+                    println!("no market selected. run: market <base> <quote>");
+                    continue;
+                };
+                exchange.market(&symbol).unwrap().show_trades();
+            }
+
+            "save" => {
+                let Some(symbol) = current_market.clone() else {
+                    println!("no market selected. run: market <base> <quote>");
+                    continue;
+                };
+                if parts.len() != 2 {
+                    println!("Usage: save <file>");
+                    continue;
+                }
+
+                match exchange.market(&symbol).unwrap().save_to_file(parts[1]) {
+                    Ok(()) => println!("market {} saved to {}.", symbol, parts[1]),
+                    Err(e) => println!("failed to save: {}", e),
+                }
+            }
+
+            "load" => {
+                let Some(symbol) = current_market.clone() else {
+                    println!("no market selected. run: market <base> <quote>");
+                    continue;
+                };
+                if parts.len() != 2 {
+                    println!("Usage: load <file>");
+                    continue;
+                }
+
+                match OrderBook::load_from_file(parts[1]) {
+                    Ok(book) => {
+                        exchange.markets.insert(symbol.clone(), book);
+                        println!("market {} restored from {}.", symbol, parts[1]);
+                    }
+                    Err(e) => println!("failed to load: {}", e),
+                }
+            }
+
+            "log" => {
+                let Some(symbol) = current_market.clone() else {
+                    println!("no market selected. run: market <base> <quote>");
+                    continue;
+                };
+                if parts.len() != 2 {
+                    println!("Usage: log <file>");
+                    continue;
+                }
+
+                let book = exchange.market_mut(&symbol).unwrap();
+                match book.enable_event_log(parts[1]) {
+                    Ok(()) => println!("logging trades for {} to {}.", symbol, parts[1]),
+                    Err(e) => println!("failed to open event log: {}", e),
+                }
             }
 
             _ => {
-                println!("unknown command. try: add / book / trades / exit");
+                println!("unknown command. try: market / add / cancel / book / trades / save / load / log / exit");
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_scaled_whole_and_fractional() {
+        assert_eq!(parse_scaled("10"), Ok(100_000));
+        assert_eq!(parse_scaled("10.5"), Ok(105_000));
+        assert_eq!(parse_scaled("0.0001"), Ok(1));
+        assert_eq!(parse_scaled(".25"), Ok(2_500));
+    }
+
+    #[test]
+    fn parse_scaled_negative() {
+        assert_eq!(parse_scaled("-10.5"), Ok(-105_000));
+        assert_eq!(parse_scaled("-0.0001"), Ok(-1));
+    }
+
+    #[test]
+    fn parse_scaled_rejects_too_many_fractional_digits() {
+        assert_eq!(parse_scaled("1.00001"), Err(()));
+    }
+
+    #[test]
+    fn parse_scaled_rejects_multiple_decimal_points() {
+        assert_eq!(parse_scaled("1.2.3"), Err(()));
+    }
+
+    #[test]
+    fn parse_scaled_rejects_empty_and_garbage() {
+        assert_eq!(parse_scaled(""), Err(()));
+        assert_eq!(parse_scaled("abc"), Err(()));
+    }
+
+    #[test]
+    fn format_scaled_round_trips_through_parse() {
+        for s in ["10.0000", "0.0001", "-5.2500"] {
+            assert_eq!(format_scaled(parse_scaled(s).unwrap()), s);
+        }
+    }
+
+    #[test]
+    fn fill_or_kill_is_rejected_atomically_when_liquidity_is_insufficient() {
+        let mut book = OrderBook::new();
+        let sell_id = book.next_order_id();
+        book.add_order(Order::new_limit(
+            sell_id,
+            OrderType::Sell,
+            "10".parse().unwrap(),
+            "3".parse().unwrap(),
+            TimeInForce::GoodTilCancel,
+        ));
+
+        let fok_id = book.next_order_id();
+        let outcome = book.add_order(Order::new_limit(
+            fok_id,
+            OrderType::Buy,
+            "10".parse().unwrap(),
+            "5".parse().unwrap(),
+            TimeInForce::FillOrKill,
+        ));
+
+        assert_eq!(outcome, OrderOutcome::RejectedFillOrKill);
+        assert!(book.trades.is_empty());
+        assert_eq!(
+            book.remaining_quantity(sell_id, OrderType::Sell),
+            Some("3".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn market_sell_trades_at_the_resting_buy_order_price() {
+        let mut book = OrderBook::new();
+        let buy_id = book.next_order_id();
+        book.add_order(Order::new_limit(
+            buy_id,
+            OrderType::Buy,
+            "100".parse().unwrap(),
+            "5".parse().unwrap(),
+            TimeInForce::GoodTilCancel,
+        ));
+
+        let sell_id = book.next_order_id();
+        book.add_order(Order::new_market(sell_id, OrderType::Sell, "3".parse().unwrap()));
+
+        assert_eq!(book.trades.len(), 1);
+        assert_eq!(book.trades[0].price, "100".parse().unwrap());
+    }
+
+    #[test]
+    fn market_buy_trades_at_the_resting_sell_order_price() {
+        let mut book = OrderBook::new();
+        let sell_id = book.next_order_id();
+        book.add_order(Order::new_limit(
+            sell_id,
+            OrderType::Sell,
+            "100".parse().unwrap(),
+            "5".parse().unwrap(),
+            TimeInForce::GoodTilCancel,
+        ));
+
+        let buy_id = book.next_order_id();
+        book.add_order(Order::new_market(buy_id, OrderType::Buy, "3".parse().unwrap()));
+
+        assert_eq!(book.trades.len(), 1);
+        assert_eq!(book.trades[0].price, "100".parse().unwrap());
+    }
+
+    #[test]
+    fn snapshot_restore_round_trips_book_state() {
+        let mut book = OrderBook::new();
+        let buy_id = book.next_order_id();
+        book.add_order(Order::new_limit(
+            buy_id,
+            OrderType::Buy,
+            "100".parse().unwrap(),
+            "5".parse().unwrap(),
+            TimeInForce::GoodTilCancel,
+        ));
+        let sell_id = book.next_order_id();
+        book.add_order(Order::new_limit(
+            sell_id,
+            OrderType::Sell,
+            "150".parse().unwrap(),
+            "2".parse().unwrap(),
+            TimeInForce::GoodTilCancel,
+        ));
+
+        let restored = OrderBook::restore(book.snapshot());
+
+        assert_eq!(restored.next_id, book.next_id);
+        assert_eq!(restored.next_seq, book.next_seq);
+        assert_eq!(
+            restored.remaining_quantity(buy_id, OrderType::Buy),
+            book.remaining_quantity(buy_id, OrderType::Buy)
+        );
+        assert_eq!(
+            restored.remaining_quantity(sell_id, OrderType::Sell),
+            book.remaining_quantity(sell_id, OrderType::Sell)
+        );
+        assert_eq!(restored.best_quote(), book.best_quote());
+    }
+
+    #[test]
+    fn save_and_load_round_trips_through_json() {
+        let mut book = OrderBook::new();
+        let buy_id = book.next_order_id();
+        book.add_order(Order::new_limit(
+            buy_id,
+            OrderType::Buy,
+            "100".parse().unwrap(),
+            "5".parse().unwrap(),
+            TimeInForce::GoodTilCancel,
+        ));
+
+        let path = std::env::temp_dir().join(format!(
+            "orderbook_test_{}_{}.json",
+            std::process::id(),
+            buy_id
+        ));
+        let path = path.to_str().unwrap();
+
+        book.save_to_file(path).unwrap();
+        let loaded = OrderBook::load_from_file(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            loaded.remaining_quantity(buy_id, OrderType::Buy),
+            book.remaining_quantity(buy_id, OrderType::Buy)
+        );
+    }
+}